@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// A single assembly-time diagnostic: a message anchored to a source
+/// line and column, rendered with a caret into the offending line so a
+/// user can spot the mistake without an external toolchain.
+#[derive(Debug)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    source_line: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "error: {} (line {}, column {})", self.message, self.line, self.column)?;
+        writeln!(f, "  | {}", self.source_line)?;
+        write!(f, "  | {}^", " ".repeat(self.column.saturating_sub(1)))
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+struct ParsedLine {
+    label: Option<Token>,
+    mnemonic: Option<Token>,
+    operands: Vec<Token>,
+    line: usize,
+    raw: String,
+}
+
+const DIRECTIVES: &[&str] = &[".ORIG", ".FILL", ".BLKW", ".STRINGZ", ".END"];
+const OPCODES: &[&str] = &[
+    "ADD", "AND", "NOT", "BR", "BRN", "BRZ", "BRP", "BRNZ", "BRNP", "BRZP", "BRNZP", "JMP",
+    "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI", "STR", "TRAP", "RTI", "RET",
+    "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT",
+];
+
+fn strip_comment(line: &str) -> &str {
+    match line.find(';') {
+        Some(idx) => &line[..idx],
+        None => line,
+    }
+}
+
+fn tokenize_line(line: &str, line_no: usize) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+            continue;
+        }
+        if c == '"' {
+            chars.next();
+            let str_start = start;
+            while let Some(&(_, c2)) = chars.peek() {
+                chars.next();
+                if c2 == '"' {
+                    break;
+                }
+            }
+            let end = chars.peek().map(|&(i, _)| i).unwrap_or(line.len());
+            tokens.push(Token {
+                text: line[str_start..end].to_string(),
+                line: line_no,
+                column: str_start + 1,
+            });
+            continue;
+        }
+        let mut end = start;
+        while let Some(&(i, c2)) = chars.peek() {
+            if c2.is_whitespace() || c2 == ',' {
+                break;
+            }
+            end = i + c2.len_utf8();
+            chars.next();
+        }
+        tokens.push(Token {
+            text: line[start..end].to_string(),
+            line: line_no,
+            column: start + 1,
+        });
+    }
+    tokens
+}
+
+fn is_opcode_or_directive(text: &str) -> bool {
+    let upper = text.to_uppercase();
+    DIRECTIVES.contains(&upper.as_str()) || OPCODES.contains(&upper.as_str())
+}
+
+fn err(tok: &Token, raw: &str, message: impl Into<String>) -> AsmError {
+    AsmError {
+        line: tok.line,
+        column: tok.column,
+        message: message.into(),
+        source_line: raw.to_string(),
+    }
+}
+
+fn parse_number(tok: &Token, raw: &str) -> Result<u16, AsmError> {
+    let text = &tok.text;
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.as_str()),
+    };
+    let value = if let Some(hex) = digits.strip_prefix('x').or_else(|| digits.strip_prefix('X')) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(dec) = digits.strip_prefix('#') {
+        dec.parse::<i64>()
+    } else {
+        digits.parse::<i64>()
+    }
+    .map_err(|_| err(tok, raw, format!("expected a number, found `{}`", text)))?;
+    let value = if negative { -value } else { value };
+    Ok(value as i16 as u16)
+}
+
+fn parse_register(tok: &Token, raw: &str) -> Result<u16, AsmError> {
+    let upper = tok.text.to_uppercase();
+    if let Some(rest) = upper.strip_prefix('R') {
+        if let Ok(n) = rest.parse::<u16>() {
+            if n <= 7 {
+                return Ok(n);
+            }
+        }
+    }
+    Err(err(tok, raw, format!("expected a register R0-R7, found `{}`", tok.text)))
+}
+
+fn sext_fits(value: u16, bits: u32) -> bool {
+    let signed = value as i16 as i32;
+    let min = -(1i32 << (bits - 1));
+    let max = (1i32 << (bits - 1)) - 1;
+    signed >= min && signed <= max
+}
+
+fn pc_offset(
+    tok: &Token,
+    raw: &str,
+    symbols: &HashMap<String, u16>,
+    instr_addr: u16,
+    bits: u32,
+) -> Result<u16, AsmError> {
+    let target = match symbols.get(&tok.text.to_uppercase()) {
+        Some(addr) => *addr,
+        None => return Err(err(tok, raw, format!("undefined label `{}`", tok.text))),
+    };
+    let offset = target.wrapping_sub(instr_addr.wrapping_add(1));
+    if !sext_fits(offset, bits) {
+        return Err(err(
+            tok,
+            raw,
+            format!("label `{}` is out of range for a {}-bit PC-relative offset", tok.text, bits),
+        ));
+    }
+    Ok(offset & ((1 << bits) - 1))
+}
+
+/// Size, in words, that a line occupies in the final object image.
+fn line_size(mnemonic: &str, operands: &[Token]) -> u16 {
+    match mnemonic.to_uppercase().as_str() {
+        ".BLKW" => operands
+            .first()
+            .and_then(|t| parse_number(t, "").ok())
+            .unwrap_or(0),
+        ".STRINGZ" => operands
+            .first()
+            .map(|t| t.text.trim_matches('"').len() as u16 + 1)
+            .unwrap_or(1),
+        ".END" | ".ORIG" => 0,
+        _ => 1,
+    }
+}
+
+/// Assembles LC-3 assembly source into the big-endian object format
+/// `VM::read_program` understands: a leading origin word followed by one
+/// word per instruction/datum. `VM::assemble_and_load` calls this and
+/// copies the result into memory.
+pub fn assemble(source: &str) -> Result<Vec<u16>, Vec<AsmError>> {
+    let mut errors: Vec<AsmError> = Vec::new();
+    let raw_lines: Vec<&str> = source.lines().collect();
+
+    let mut parsed_lines = Vec::new();
+    for (i, raw_line) in raw_lines.iter().enumerate() {
+        let line_no = i + 1;
+        let without_comment = strip_comment(raw_line);
+        if without_comment.trim().is_empty() {
+            continue;
+        }
+        let tokens = tokenize_line(without_comment, line_no);
+        if tokens.is_empty() {
+            continue;
+        }
+        let mut iter = tokens.into_iter();
+        let first = iter.next().unwrap();
+        let (label, mnemonic) = if is_opcode_or_directive(&first.text) {
+            (None, Some(first))
+        } else {
+            (Some(first), iter.next())
+        };
+        let operands: Vec<Token> = iter.collect();
+        parsed_lines.push(ParsedLine {
+            label,
+            mnemonic,
+            operands,
+            line: line_no,
+            raw: raw_line.to_string(),
+        });
+    }
+
+    /* PASS ONE: build the symbol table by walking the location counter */
+    let mut symbols: HashMap<String, u16> = HashMap::new();
+    let mut origin: Option<u16> = None;
+    let mut loc: u16 = 0;
+    for pl in &parsed_lines {
+        let mnemonic = match &pl.mnemonic {
+            Some(m) => m,
+            None => continue,
+        };
+        if mnemonic.text.eq_ignore_ascii_case(".orig") {
+            match pl.operands.first() {
+                Some(tok) => match parse_number(tok, &pl.raw) {
+                    Ok(value) => {
+                        origin = Some(value);
+                        loc = value;
+                    }
+                    Err(e) => errors.push(e),
+                },
+                None => errors.push(err(mnemonic, &pl.raw, ".ORIG requires an address")),
+            }
+            continue;
+        }
+        if origin.is_none() {
+            errors.push(err(mnemonic, &pl.raw, "instruction appears before .ORIG"));
+            continue;
+        }
+        if let Some(label) = &pl.label {
+            let key = label.text.to_uppercase();
+            match symbols.entry(key) {
+                std::collections::hash_map::Entry::Occupied(_) => {
+                    errors.push(err(label, &pl.raw, format!("duplicate label `{}`", label.text)));
+                }
+                std::collections::hash_map::Entry::Vacant(e) => {
+                    e.insert(loc);
+                }
+            }
+        }
+        loc = loc.wrapping_add(line_size(&mnemonic.text, &pl.operands));
+    }
+
+    let origin = match origin {
+        Some(o) => o,
+        None => {
+            errors.push(AsmError {
+                line: 1,
+                column: 1,
+                message: "program is missing a .ORIG directive".to_string(),
+                source_line: raw_lines.first().unwrap_or(&"").to_string(),
+            });
+            return Err(errors);
+        }
+    };
+
+    /* PASS TWO: emit each instruction/datum at its final address */
+    let mut image: Vec<u16> = Vec::new();
+    let mut addr = origin;
+    for pl in &parsed_lines {
+        let mnemonic = match &pl.mnemonic {
+            Some(m) => m,
+            None => continue,
+        };
+        let op = mnemonic.text.to_uppercase();
+        if op == ".ORIG" {
+            continue;
+        }
+        if op == ".END" {
+            break;
+        }
+        match encode(&op, mnemonic, &pl.operands, &pl.raw, &symbols, addr) {
+            Ok(words) => {
+                addr = addr.wrapping_add(words.len() as u16);
+                image.extend(words);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut object = Vec::with_capacity(image.len() + 1);
+    object.push(origin);
+    object.extend(image);
+    Ok(object)
+}
+
+fn encode(
+    op: &str,
+    mnemonic: &Token,
+    operands: &[Token],
+    raw: &str,
+    symbols: &HashMap<String, u16>,
+    addr: u16,
+) -> Result<Vec<u16>, AsmError> {
+    let need = |n: usize| -> Result<(), AsmError> {
+        if operands.len() < n {
+            Err(err(mnemonic, raw, format!("{} expects {} operand(s)", op, n)))
+        } else {
+            Ok(())
+        }
+    };
+
+    match op {
+        "ADD" | "AND" => {
+            need(3)?;
+            let dr = parse_register(&operands[0], raw)?;
+            let sr1 = parse_register(&operands[1], raw)?;
+            let base = if op == "ADD" { 0b0001 } else { 0b0101 };
+            let word = if operands[2].text.starts_with('R') || operands[2].text.starts_with('r') {
+                let sr2 = parse_register(&operands[2], raw)?;
+                (base << 12) | (dr << 9) | (sr1 << 6) | sr2
+            } else {
+                let imm5 = parse_number(&operands[2], raw)?;
+                if !sext_fits(imm5, 5) {
+                    return Err(err(&operands[2], raw, "immediate does not fit in 5 bits"));
+                }
+                (base << 12) | (dr << 9) | (sr1 << 6) | (1 << 5) | (imm5 & 0x1F)
+            };
+            Ok(vec![word])
+        }
+        "NOT" => {
+            need(2)?;
+            let dr = parse_register(&operands[0], raw)?;
+            let sr = parse_register(&operands[1], raw)?;
+            Ok(vec![(0b1001 << 12) | (dr << 9) | (sr << 6) | 0x3F])
+        }
+        "BR" | "BRN" | "BRZ" | "BRP" | "BRNZ" | "BRNP" | "BRZP" | "BRNZP" => {
+            need(1)?;
+            let flags = if op == "BR" {
+                0b111
+            } else {
+                let rest = &op[2..];
+                let mut f = 0u16;
+                if rest.contains('N') {
+                    f |= 0b100;
+                }
+                if rest.contains('Z') {
+                    f |= 0b010;
+                }
+                if rest.contains('P') {
+                    f |= 0b001;
+                }
+                f
+            };
+            let offset = pc_offset(&operands[0], raw, symbols, addr, 9)?;
+            Ok(vec![(flags << 9) | offset])
+        }
+        "JMP" => {
+            need(1)?;
+            let r = parse_register(&operands[0], raw)?;
+            Ok(vec![(0b1100 << 12) | (r << 6)])
+        }
+        "RET" => Ok(vec![(0b1100 << 12) | (7 << 6)]),
+        "JSR" => {
+            need(1)?;
+            let offset = pc_offset(&operands[0], raw, symbols, addr, 11)?;
+            Ok(vec![(0b0100 << 12) | (1 << 11) | offset])
+        }
+        "JSRR" => {
+            need(1)?;
+            let r = parse_register(&operands[0], raw)?;
+            Ok(vec![(0b0100 << 12) | (r << 6)])
+        }
+        "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+            need(2)?;
+            let r = parse_register(&operands[0], raw)?;
+            let offset = pc_offset(&operands[1], raw, symbols, addr, 9)?;
+            let base = match op {
+                "LD" => 0b0010,
+                "LDI" => 0b1010,
+                "LEA" => 0b1110,
+                "ST" => 0b0011,
+                "STI" => 0b1011,
+                _ => unreachable!(),
+            };
+            Ok(vec![(base << 12) | (r << 9) | offset])
+        }
+        "LDR" | "STR" => {
+            need(3)?;
+            let r = parse_register(&operands[0], raw)?;
+            let baser = parse_register(&operands[1], raw)?;
+            let offset = parse_number(&operands[2], raw)?;
+            if !sext_fits(offset, 6) {
+                return Err(err(&operands[2], raw, "offset does not fit in 6 bits"));
+            }
+            let base = if op == "LDR" { 0b0110 } else { 0b0111 };
+            Ok(vec![(base << 12) | (r << 9) | (baser << 6) | (offset & 0x3F)])
+        }
+        "TRAP" => {
+            need(1)?;
+            let vector = parse_number(&operands[0], raw)?;
+            Ok(vec![(0b1111 << 12) | (vector & 0xFF)])
+        }
+        "GETC" => Ok(vec![0xF000 | 0x20]),
+        "OUT" => Ok(vec![0xF000 | 0x21]),
+        "PUTS" => Ok(vec![0xF000 | 0x22]),
+        "IN" => Ok(vec![0xF000 | 0x23]),
+        "PUTSP" => Ok(vec![0xF000 | 0x24]),
+        "HALT" => Ok(vec![0xF000 | 0x25]),
+        "RTI" => Ok(vec![0b1000 << 12]),
+        ".FILL" => {
+            need(1)?;
+            Ok(vec![parse_number(&operands[0], raw)?])
+        }
+        ".BLKW" => {
+            need(1)?;
+            let count = parse_number(&operands[0], raw)?;
+            Ok(vec![0; count as usize])
+        }
+        ".STRINGZ" => {
+            need(1)?;
+            let text = operands[0].text.trim_matches('"');
+            let mut words: Vec<u16> = text.chars().map(|c| c as u16).collect();
+            words.push(0);
+            Ok(words)
+        }
+        _ => Err(err(mnemonic, raw, format!("unknown mnemonic `{}`", mnemonic.text))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_assemble_labeled_loop() {
+        let source = "\
+.ORIG x3000
+AND R0, R0, #0
+LOOP ADD R0, R0, #1
+BRnzp LOOP
+HALT
+.END
+";
+        let object = assemble(source).unwrap();
+        assert_eq!(object, vec![0x3000, 0x5020, 0x1021, 0x0FFE, 0xF025]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_pc_relative_offset() {
+        let mut source = String::from(".ORIG x3000\nBR FAR\n");
+        for _ in 0..300 {
+            source.push_str(".FILL x0000\n");
+        }
+        source.push_str("FAR ADD R0, R0, #0\n.END\n");
+
+        let errors = assemble(&source).unwrap_err();
+        assert!(
+            errors.iter().any(|e| e.message.contains("out of range")),
+            "expected an out-of-range PC-relative offset error, got {:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_labels() {
+        let source = "\
+.ORIG x3000
+DUP ADD R0, R0, #0
+DUP ADD R0, R0, #1
+.END
+";
+        let errors = assemble(source).unwrap_err();
+        assert!(
+            errors.iter().any(|e| e.message.contains("duplicate label")),
+            "expected a duplicate label error, got {:?}",
+            errors
+        );
+    }
+}
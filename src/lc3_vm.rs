@@ -1,513 +1,1128 @@
-pub mod lc3_vm {
-    use std::io::stdin;
-    use std::io::stdout;
-    use std::io::Read;
-    use std::io::Write;
-    use std::fs::File;
-
-    const KEYBOARD_STATUS_REGISTER: u16 = 0xFE00;
-    const KEYBOARD_DATA_REGISTER: u16 = 0xFE02;
-
-    #[allow(dead_code)]
-    pub enum Registers {
-        R0,
-        R1,
-        R2,
-        R3,
-        R4,
-        R5,
-        R6,
-        R7,
-        ProgramCounter,
-        Condition,
-        Count,
-    }
-
-    #[derive(Debug)]
-    #[allow(dead_code)]
-    enum OperationCodes {
-        Branch,
-        Add,
-        Load,
-        Store,
-        JumpRegister,
-        And,
-        LoadRegister,
-        StoreRegister,
-        Unused,
-        Not,
-        LoadIndirect,
-        StoreIndirect,
-        Jump,
-        Reserved,
-        LoadEffectiveAddress,
-        Trap,
-    }
-
-    impl OperationCodes {
-        fn from_integer(x: u16) -> OperationCodes {
-            unsafe { std::mem::transmute::<u8, OperationCodes>(x as u8) }
-        }
-    }
-
-    const TRAP_GET_CHARACTER: u16 = 0x20;
-    const TRAP_OUT: u16 = 0x21;
-    const TRAP_PUTS: u16 = 0x22;
-    const TRAP_IN: u16 = 0x23;
-    const TRAP_PUTS_TWO: u16 = 0x24;
-    const TRAP_HALT: u16 = 0x25;
-
-    const POSITIVE: u16 = 1 << 0;
-    const ZERO: u16 = 1 << 1;
-    const NEGATIVE: u16 = 1 << 2;
-
-    pub struct VM {
-        memory: [u16; std::u16::MAX as usize + 1],
-        registers: [u16; Registers::Count as usize + 1],
-        running: bool,
-    }
-
-    impl VM {
-        pub fn new() -> VM {
-            VM {
-                registers: [0; crate::lc3_vm::lc3_vm::Registers::Count as usize + 1],
-                memory: [0; std::u16::MAX as usize + 1],
-                running: false,
-            }
-        }
-
-        pub fn start(&mut self, program: File) {
-            self.read_program(program);
-            let start_position: u16 = 0x3000;
-
-            self.registers[Registers::ProgramCounter as usize] = start_position;
-
-            self.running = true;
-            while self.running {
-                /* FETCH */
-                let instr = self.mem_read(self.registers[Registers::ProgramCounter as usize]);
-                let op = instr >> 12;
-                // println!(
-                //     "instruction {:#b} for op {:?}",
-                //     instr,
-                //     OperationCodes::from_integer(op)
-                // );
-                self.registers[Registers::ProgramCounter as usize] += 1; // Post increment program counter
-                // println!(
-                //     "Incremented program counter is {}",
-                //     self.reg[Registers::ProgramCounter as usize]
-                // );
-                match OperationCodes::from_integer(op) {
-                    OperationCodes::Add => self.add(instr),
-                    OperationCodes::And => self.and(instr),
-                    OperationCodes::Not => self.not(instr),
-                    OperationCodes::Branch => self.branch(instr),
-                    OperationCodes::Jump => self.jump(instr),
-                    OperationCodes::JumpRegister => self.jump_register(instr),
-                    OperationCodes::Load => self.load(instr),
-                    OperationCodes::LoadIndirect => self.load_indirect(instr),
-                    OperationCodes::LoadRegister => self.load_register(instr),
-                    OperationCodes::LoadEffectiveAddress => self.load_effective_address(instr),
-                    OperationCodes::Store => self.store(instr),
-                    OperationCodes::StoreIndirect => self.store_indirect(instr),
-                    OperationCodes::StoreRegister => self.store_register(instr),
-                    OperationCodes::Trap => self.trap(instr),
-                    _ => panic!("Unknown instruction {:#b}", instr),
-                }
-            }
-        }
-
-        fn read_program(&mut self, mut program: File) {
-            let mut buffer: [u8; 2] = [0; 2];
-            program.read(&mut buffer).expect("Failed to read origin.");
-            let mut origin = swap_endian(buffer);
-            // println!("origin: {:#b}", origin);
-            loop {
-                match program.read(&mut buffer) {
-                    Ok(2) => {
-                        self.memory[origin as usize] = swap_endian(buffer);
-                        origin = origin + 1;
-                    }
-                    Ok(0) => break,
-                    Ok(_) => {
-                        panic!("Unexpected error reading program.");
-                    }
-                    Err(_) => {
-                        panic!("Unexpected error reading program.");
-                    }
-                }
-            }
-        }
-
-                /// ### Assembler Formats
-        /// **ADD DR, SR1, SR2 \
-        /// ADD DR, SR1, imm5**
-        ///
-        /// ### Encodings
-        /// | 0001  | SR1  | 0   | 00  | SR2 |
-        /// |-------|------|-----|-----|-----|
-        /// | 15...12 | 11..9 | 8..6 | 4..3 | 2..0 |
-        ///
-        ///
-        /// | 0001  | SR1  | 0   | imm5  |
-        /// |-------|------|-----|-----|
-        /// | 15...12 | 11..9 | 8..6 | 4..0|
-        /// ### Operation
-        /// if (bit[5] == 0)
-        /// 	DR = SR1 + SR2;
-        /// else
-        /// 	DR = SR1 + SEXT(imm5);
-        /// setcc();
-        ///
-        /// ### Description
-        /// If bit [5] is 0, the second source operand is obtained from SR2. If bit [5] is 1, the second source operand is obtained by sign-extending the imm5 field to 16 bits. In both cases, the second source operand is added to the contents of SR1 and the result stored in DR. The condition codes are set, based on whether the result is negative, zero, or positive.
-        ///
-        /// ### Examples
-        /// ADD R2, R3, R4 ; R2 ← R3 + R4
-        /// ```rust
-        /// let mut vm = VM::new();
-        /// vm.registers[Registers::R2 as usize] = 0;
-        /// vm.registers[Registers::R3 as usize] = 1;
-        /// vm.registers[Registers::R4 as usize] = 3;
-        /// vm.add(0b0001_010_011_0_00_100);
-        ///
-        /// assert_eq!(vm.registers[Registers::R2 as usize], 4, "Could not add indirectly!");
-        /// assert_eq!(vm.registers[Registers::Condition as usize], POSITIVE, "Condition register not updated correctly!")
-        /// ```
-        /// ADD R2, R3, #7 ; R2 ← R3 + 7
-        /// ```rust
-        /// let mut vm = VM::new();
-        /// vm.registers[Registers::R2 as usize] = 0;
-        /// vm.registers[Registers::R3 as usize] = 1;
-        /// vm.add(0b0001_010_011_1_10010);
-        ///
-        /// assert_eq!(vm.registers[Registers::R2 as usize], 65523, "Could not add immediately!"); //Two's complement
-        /// assert_eq!(vm.registers[Registers::Condition as usize], NEGATIVE, "Condition register not updated correctly!")
-        /// ```
-        fn add(&mut self, instr: u16) {
-            /* destination register (DR) */
-            let r0 = (instr >> 9) & 0x7;
-            // println!("Adding, was {}", self.reg[r0 as usize]);
-
-            /* first operand (SR1) */
-            let r1 = (instr >> 6) & 0x7;
-            /* whether we are in immediate mode */
-            let imm_flag = (instr >> 5) & 0x1;
-
-            if imm_flag > 0 {
-                let imm5 = sign_extend(instr & 0x1F, 5);
-                self.registers[r0 as usize] = self.registers[r1 as usize] + imm5;
-            } else {
-                let r2 = instr & 0x7;
-                self.registers[r0 as usize] = self.registers[r1 as usize] + self.registers[r2 as usize];
-            }
-            // println!("Adding, is now {}", self.reg[r0 as usize]);
-            self.update_flags(r0);
-        }
-
-        fn and(&mut self, instr: u16) {
-            /* destination register (DR) */
-            let r0 = (instr >> 9) & 0x7;
-            /* first operand (SR1) */
-            let r1 = (instr >> 6) & 0x7;
-            /* whether we are in immediate mode */
-            let imm_flag = (instr >> 5) & 0x1;
-            if imm_flag > 0 {
-                let imm5 = sign_extend(instr & 0x1F, 5);
-                self.registers[r0 as usize] = self.registers[r1 as usize] & imm5;
-            } else {
-                let r2 = instr & 0x7;
-                self.registers[r0 as usize] = self.registers[r1 as usize] & self.registers[r2 as usize];
-            }
-            self.update_flags(r0);
-        }
-
-        fn not(&mut self, instr: u16) {
-            /* destination register (DR) */
-            let r0 = (instr >> 9) & 0x7;
-            /* operand (SR) */
-            let r1 = (instr >> 6) & 0x7;
-
-            self.registers[r0 as usize] = !(self.registers[r1 as usize]);
-            self.update_flags(r0);
-        }
-
-        fn branch(&mut self, instr: u16) {
-            let pc_offset = sign_extend((instr) & 0x1ff, 9);
-            let cond_flag = (instr >> 9) & 0x7;
-            // println!(
-            //     "cond flag: {:#b} if test {}",
-            //     cond_flag,
-            //     (cond_flag & self.reg[Registers::Condition as usize])
-            // );
-            if cond_flag & self.registers[Registers::Condition as usize] > 0 {
-                // println!(
-                //     "True branch! Program counter was {}",
-                //     self.reg[Registers::ProgramCounter as usize]
-                // );
-                self.registers[Registers::ProgramCounter as usize] += pc_offset;
-                // println!(
-                //     "True branch! New program counter is {}",
-                //     self.reg[Registers::ProgramCounter as usize]
-                // );
-            }
-        }
-
-        fn jump(&mut self, instr: u16) {
-            /* Also handles RET */
-            let r1 = (instr >> 6) & 0x7;
-            self.registers[Registers::ProgramCounter as usize] = self.registers[r1 as usize];
-        }
-
-        fn jump_register(&mut self, instr: u16) {
-            self.registers[Registers::R7 as usize] = self.registers[Registers::ProgramCounter as usize];
-            let jsr = (instr >> 11) & 1;
-            if jsr > 0 {
-                let pc_offset = sign_extend(instr & 0x7FF, 11);
-                self.registers[Registers::ProgramCounter as usize] += pc_offset;
-            } else {
-                //jsrr
-                self.registers[Registers::ProgramCounter as usize] = (instr >> 6) & 0x7;
-            }
-        }
-
-        fn load(&mut self, instr: u16) {
-            /* destination register (DR) */
-            let r0 = (instr >> 9) & 0x7;
-            /* PCoffset 9*/
-            let pc_offset = sign_extend(instr & 0x1ff, 9);
-            /* add pc_offset to the current PC, look at that memory location to get the final address */
-            let loaded = self.mem_read(self.registers[Registers::ProgramCounter as usize] + pc_offset);
-            self.registers[r0 as usize] = loaded;
-            self.update_flags(r0);
-        }
-
-        fn update_flags(&mut self, r: u16) {
-            //println!("Updating flags!");
-            let r_val = self.registers[r as usize];
-            self.registers[Registers::Condition as usize] = if r_val == 0 {
-                ZERO
-            } else if (r_val >> 15) > 0 {
-                NEGATIVE
-            } else {
-                POSITIVE
-            }
-        }
-
-        fn load_indirect(&mut self, instr: u16) {
-            /* destination register (DR) */
-            let r0 = (instr >> 9) & 0x7;
-            /* PCoffset 9*/
-            let pc_offset = sign_extend(instr & 0x1ff, 9);
-            /* add pc_offset to the current PC, look at that memory location to get the final address */
-            let read = self.mem_read(self.registers[Registers::ProgramCounter as usize] + pc_offset);
-            //println!("register to read ldi: {:#b}", read);
-            self.registers[r0 as usize] = self.mem_read(read);
-            //println!("Contents of that register: {:#b}", self.reg[r0 as usize]);
-            self.update_flags(r0);
-        }
-
-        fn load_register(&mut self, instr: u16) {
-            // 0x40 ? 64 / 16 =  4 ???
-            let offset: u16 = sign_extend(instr & 0b11_1111, 6);
-
-            let dr = (instr >> 9) & 0b111;
-            let baser = (instr >> 6) & 0b111;
-
-            self.registers[dr as usize] = self.mem_read(self.registers[baser as usize] + offset);
-            self.update_flags(dr);
-        }
-
-        fn load_effective_address(&mut self, instr: u16) {
-            /* destination register (DR) */
-            let r0 = (instr >> 9) & 0x7;
-            /* PCoffset 9*/
-            let pc_offset = sign_extend(instr & 0x1ff, 9);
-            /* add pc_offset to the current PC, look at that memory location to get the final address */
-            self.registers[r0 as usize] = self.registers[Registers::ProgramCounter as usize] + pc_offset;
-            self.update_flags(r0);
-        }
-
-        fn store(&mut self, instr: u16) {
-            let sr = (instr >> 9) & 0x7;
-            /* PCoffset 9*/
-            let pc_offset = sign_extend(instr & 0x1ff, 9);
-            self.mem_write(
-                self.registers[Registers::ProgramCounter as usize] + pc_offset,
-                self.registers[sr as usize],
-            );
-        }
-
-        fn store_indirect(&mut self, instr: u16) {
-            let sr = (instr >> 9) & 0x7;
-            /* PCoffset 9*/
-            let pc_offset = sign_extend(instr & 0x1ff, 9);
-            let read = self.mem_read(self.registers[Registers::ProgramCounter as usize] + pc_offset);
-            self.mem_write(read, self.registers[sr as usize]);
-        }
-
-        fn store_register(&mut self, instr: u16) {
-            let sr = (instr >> 9) & 0x7;
-            /* PCoffset 6*/
-            let offset: u16 = sign_extend(instr & 0b11_1111, 6);
-            let baser = (instr >> 6) & 0b111;
-            self.mem_write(self.registers[baser as usize] + offset, self.registers[sr as usize]);
-        }
-
-        fn trap(&mut self, instr: u16) {
-            //println!("complete trap instruction {:#b}", instr);
-            //println!("Got trap {:#b} or in hex {:#x}", instr & 0xFF, instr & 0xFF);
-            match instr & 0xFF {
-                TRAP_GET_CHARACTER => self.get_character(),
-                TRAP_OUT => self.out(),
-                TRAP_PUTS => self.puts(),
-                TRAP_IN => self.scan(),
-                TRAP_PUTS_TWO => self.putsp(),
-                TRAP_HALT => self.halt(),
-                _ => panic!("Unknown trap code {:#b}", instr)
-            }
-        }
-
-        fn get_character(&mut self) {
-            //TODO ignore enter
-            let input: u16 = std::io::stdin()
-                .bytes()
-                .next()
-                .and_then(|result| result.ok())
-                .map(|byte| byte as u16)
-                .expect("Could not read character!");
-            //println!("char was {}", input);
-            self.registers[Registers::R0 as usize] = input & 0b1111_1111;
-        }
-
-        fn out(&mut self) {
-            print!("{}", self.registers[Registers::R0 as usize] as u8 as char);
-            // println!(" as u16 {}", self.reg[Registers::R0 as usize]);
-            stdout().flush().expect("Could not print!");
-        }
-
-        fn puts(&mut self) {
-            let mut addr = self.registers[Registers::R0 as usize];
-            let mut character = self.memory[addr as usize];
-
-            while character > 0 {
-                print!("{}", (character & 0b1111_1111) as u8 as char); //Hmmmm.......
-                addr = addr + 1;
-                character = self.memory[addr as usize];
-            }
-            stdout().flush().expect("Could not print!");
-        }
-
-        // in
-        fn scan(&mut self) {
-            print!("Enter a character: ");
-            stdout().flush().expect("Could not print!");
-
-            self.get_character();
-        }
-
-        fn putsp(&mut self) {
-            let mut addr = self.registers[Registers::R0 as usize];
-            let mut character = self.memory[addr as usize];
-
-            while character > 0 {
-                print!("{}", (character & 0b1111_1111) as u8 as char);
-                let second_part = (character >> 8) & 0b1111_1111;
-                if second_part == 0 {
-                    break;
-                }
-                print!("{}", second_part as u8 as char);
-                addr = addr + 1;
-                character = self.memory[addr as usize];
-            }
-            stdout().flush().expect("Could not print!");
-        }
-
-        fn halt(&mut self) {
-            println!("Goodbye!");
-            self.running = false;
-        }
-
-        fn mem_write(&mut self, adress: u16, val: u16) {
-            self.memory[adress as usize] = val;
-        }
-
-        fn mem_read(&mut self, address: u16) -> u16 {
-            if address == KEYBOARD_STATUS_REGISTER {
-                // println!("Reading keyboard!");
-                match stdin().bytes().next() {
-                    //TODO is this even correct? needs a timeout?
-                    None => {
-                        // println!("Didn't read a byte from the keyboard.");
-                        self.memory[KEYBOARD_STATUS_REGISTER as usize] = 0;
-                    }
-                    Some(a_byte) => {
-                        let character = a_byte.expect("Could not read input.") as u16;
-                        // println!("Read from keyboard char: {}", character);
-                        if character != 10 {
-                            //TODO ignore enters, but thats weird........
-                            self.memory[KEYBOARD_STATUS_REGISTER as usize] = 1 << 15;
-                            self.memory[KEYBOARD_DATA_REGISTER as usize] = character;
-                        } else {
-                            self.memory[KEYBOARD_STATUS_REGISTER as usize] = 0;
-                        }
-                    }
-                }
-            }
-            self.memory[address as usize]
-        }
-    }
-
-    fn swap_endian(original: [u8; 2]) -> u16 {
-        original[1] as u16 + ((original[0] as u16) << 8) //TODO the right way?
-    }
-
-    fn sign_extend(x: u16, bit_count: i32) -> u16 {
-        let mut y = x;
-        // for negative numbers
-        if ((y >> (bit_count - 1)) & 1) > 0 {
-            y |= 0xFFFF << bit_count;
-        }
-        y
-    }
-
-
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-
-        #[test]
-        fn can_sign_extend() {
-            assert_eq!(sign_extend(0b1, 1), 65535, "Could not correctly sign extend number!");
-            assert_eq!(sign_extend(0b101, 3), 65533, "Could not correctly sign extend number!");
-        }
-
-        #[test]
-        fn can_swap_endian() {
-            assert_eq!(swap_endian([0b00000000, 0b11111111]), 0b00000000_11111111, "Could not swap endianness!")
-        }
-
-        #[test]
-        fn can_add_indirect() {
-            let mut vm = VM::new();
-            vm.registers[Registers::R2 as usize] = 0;
-            vm.registers[Registers::R3 as usize] = 1;
-            vm.registers[Registers::R4 as usize] = 3;
-            vm.add(0b0001_010_011_0_00_100);
-
-            assert_eq!(vm.registers[Registers::R2 as usize], 4, "Could not add indirectly!");
-            assert_eq!(vm.registers[Registers::Condition as usize], POSITIVE, "Condition register not updated correctly!")
-        }
-
-        #[test]
-        fn can_add_immediate() {
-            let mut vm = VM::new();
-            vm.registers[Registers::R2 as usize] = 0;
-            vm.registers[Registers::R3 as usize] = 1;
-            vm.add(0b0001_010_011_1_10010);
-
-            assert_eq!(vm.registers[Registers::R2 as usize], 65523, "Could not add immediately!"); //Two's complement
-            assert_eq!(vm.registers[Registers::Condition as usize], NEGATIVE, "Condition register not updated correctly!")
-        }
-    }
-}
\ No newline at end of file
+use std::collections::HashSet;
+use std::io::stdin;
+use std::io::stdout;
+use std::io::Read;
+use std::io::Write;
+use std::fs::File;
+use std::fmt;
+
+const KEYBOARD_STATUS_REGISTER: u16 = 0xFE00;
+const KEYBOARD_DATA_REGISTER: u16 = 0xFE02;
+const DISPLAY_STATUS_REGISTER: u16 = 0xFE04;
+const DISPLAY_DATA_REGISTER: u16 = 0xFE06;
+
+/// Processor status register: mode bit [15] (0=supervisor, 1=user),
+/// priority bits [10:8], condition codes in [2:0]. Held as VM state (see
+/// `VM::psr`) rather than a memory-mapped address, since the real LC-3 PSR
+/// is a dedicated CPU register and mapping it into ordinary memory would
+/// let a user-mode `STR`/`STI` write it directly and self-grant privilege.
+const PSR_MODE_USER: u16 = 1 << 15;
+const PSR_PRIORITY_SHIFT: u16 = 8;
+const PSR_PRIORITY_MASK: u16 = 0x7 << PSR_PRIORITY_SHIFT;
+
+/// Interrupt vector table base; vector `v` lives at `IVT_BASE + v`.
+const IVT_BASE: u16 = 0x0100;
+const VECTOR_PRIVILEGE_VIOLATION: u16 = 0x00;
+const VECTOR_KEYBOARD: u16 = 0x80;
+const VECTOR_TIMER: u16 = 0x81;
+const EXCEPTION_PRIORITY: u16 = 0b111;
+
+/// Supervisor stack base (0x3000), which grows down as RTI/interrupts
+/// push and pop PC/PSR.
+const SUPERVISOR_STACK_BASE: u16 = 0x3000;
+
+/// A memory-mapped peripheral. `mem_read`/`mem_write` consult every
+/// attached device before falling back to the backing memory array, so a
+/// device claims an address by returning `Some`/`true` and otherwise lets
+/// the request fall through.
+pub trait Device {
+    fn read(&mut self, addr: u16) -> Option<u16>;
+    fn write(&mut self, addr: u16, val: u16) -> bool;
+
+    /// Called once per fetched instruction; returns `Some((vector,
+    /// priority))` to raise an interrupt at that vector and priority
+    /// level. Devices that never interrupt can keep the default.
+    fn tick(&mut self) -> Option<(u16, u16)> {
+        None
+    }
+}
+
+/// Raises an interrupt at `VECTOR_TIMER` every `period` executed
+/// instructions, wrapping its counter back to zero instead of growing
+/// unbounded. Not attached by `VM::new` automatically: any program that
+/// doesn't set up an ISR for `VECTOR_TIMER` would start taking an
+/// unexpected interrupt every `period` instructions, so callers that want
+/// a timer must `attach_device` one themselves.
+#[allow(dead_code)]
+pub struct TimerDevice {
+    period: u32,
+    counter: u32,
+}
+
+#[allow(dead_code)]
+impl TimerDevice {
+    pub fn new(period: u32) -> TimerDevice {
+        TimerDevice { period, counter: 0 }
+    }
+}
+
+impl Device for TimerDevice {
+    fn read(&mut self, _addr: u16) -> Option<u16> {
+        None
+    }
+
+    fn write(&mut self, _addr: u16, _val: u16) -> bool {
+        false
+    }
+
+    fn tick(&mut self) -> Option<(u16, u16)> {
+        self.counter = self.counter.wrapping_add(1);
+        if self.counter >= self.period {
+            self.counter = 0;
+            Some((VECTOR_TIMER, 1))
+        } else {
+            None
+        }
+    }
+}
+
+/// Keyboard status/data registers (KBSR/KBDR). Polls stdin for a byte
+/// when the status register is read and buffers it until the data
+/// register is read. A buffered byte also raises one keyboard interrupt
+/// (see `tick`).
+struct KeyboardDevice {
+    pending: Option<u16>,
+    /// Whether `tick` has already raised an interrupt for the byte
+    /// currently in `pending`, so a keypress interrupts exactly once
+    /// instead of re-queuing on every tick until the ISR reads KBDR.
+    interrupt_raised: bool,
+}
+
+impl KeyboardDevice {
+    fn new() -> KeyboardDevice {
+        KeyboardDevice { pending: None, interrupt_raised: false }
+    }
+}
+
+impl Device for KeyboardDevice {
+    fn read(&mut self, addr: u16) -> Option<u16> {
+        match addr {
+            KEYBOARD_STATUS_REGISTER => {
+                if self.pending.is_none() {
+                    if let Some(Ok(byte)) = stdin().bytes().next() {
+                        let character = byte as u16;
+                        if character != 10 {
+                            //TODO ignore enters, but thats weird........
+                            self.pending = Some(character);
+                        }
+                    }
+                }
+                Some(if self.pending.is_some() { 1 << 15 } else { 0 })
+            }
+            KEYBOARD_DATA_REGISTER => {
+                self.interrupt_raised = false;
+                Some(self.pending.take().unwrap_or(0))
+            }
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, _addr: u16, _val: u16) -> bool {
+        false
+    }
+
+    // `tick` only reports a byte already buffered by a prior status-register
+    // poll; it never blocks on stdin itself, since it runs every fetch cycle
+    // regardless of whether the running program uses the keyboard at all.
+    // It's edge-triggered on `pending` going from absent to present rather
+    // than level-triggered, so one keypress raises exactly one interrupt
+    // instead of re-queuing on every tick until the ISR reads KBDR.
+    fn tick(&mut self) -> Option<(u16, u16)> {
+        if self.pending.is_some() && !self.interrupt_raised {
+            self.interrupt_raised = true;
+            Some((VECTOR_KEYBOARD, 4))
+        } else {
+            None
+        }
+    }
+}
+
+/// Display status/data registers (DSR/DDR). Writing a character to the
+/// data register prints it to stdout; the display is always ready.
+struct DisplayDevice;
+
+impl Device for DisplayDevice {
+    fn read(&mut self, addr: u16) -> Option<u16> {
+        match addr {
+            DISPLAY_STATUS_REGISTER => Some(1 << 15),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u16) -> bool {
+        match addr {
+            DISPLAY_DATA_REGISTER => {
+                print!("{}", (val & 0xFF) as u8 as char);
+                let _ = stdout().flush();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Fault raised while fetching, decoding, or executing a program. `start`
+/// surfaces this instead of panicking so the VM can be embedded as a
+/// library and its callers can report or recover from a failed run.
+#[derive(Debug)]
+pub enum ExecError {
+    IllegalOpcode { pc: u16, instr: u16 },
+    UnknownTrap { pc: u16, instr: u16 },
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::IllegalOpcode { pc, instr } => write!(
+                f,
+                "illegal opcode {:#06x} at pc {:#06x}",
+                instr, pc
+            ),
+            ExecError::UnknownTrap { pc, instr } => write!(
+                f,
+                "unknown trap vector {:#04x} at pc {:#06x}",
+                instr & 0xFF,
+                pc
+            ),
+            ExecError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+impl From<std::io::Error> for ExecError {
+    fn from(e: std::io::Error) -> Self {
+        ExecError::Io(e)
+    }
+}
+
+#[allow(dead_code)]
+pub enum Registers {
+    R0,
+    R1,
+    R2,
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+    ProgramCounter,
+    Condition,
+    Count,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+enum OperationCodes {
+    Branch,
+    Add,
+    Load,
+    Store,
+    JumpRegister,
+    And,
+    LoadRegister,
+    StoreRegister,
+    Rti,
+    Not,
+    LoadIndirect,
+    StoreIndirect,
+    Jump,
+    Reserved,
+    LoadEffectiveAddress,
+    Trap,
+}
+
+impl OperationCodes {
+    fn from_integer(x: u16) -> OperationCodes {
+        match x & 0xF {
+            0b0000 => OperationCodes::Branch,
+            0b0001 => OperationCodes::Add,
+            0b0010 => OperationCodes::Load,
+            0b0011 => OperationCodes::Store,
+            0b0100 => OperationCodes::JumpRegister,
+            0b0101 => OperationCodes::And,
+            0b0110 => OperationCodes::LoadRegister,
+            0b0111 => OperationCodes::StoreRegister,
+            0b1000 => OperationCodes::Rti,
+            0b1001 => OperationCodes::Not,
+            0b1010 => OperationCodes::LoadIndirect,
+            0b1011 => OperationCodes::StoreIndirect,
+            0b1100 => OperationCodes::Jump,
+            0b1101 => OperationCodes::Reserved,
+            0b1110 => OperationCodes::LoadEffectiveAddress,
+            0b1111 => OperationCodes::Trap,
+            _ => unreachable!("4-bit opcode out of range"),
+        }
+    }
+}
+
+const TRAP_GET_CHARACTER: u16 = 0x20;
+const TRAP_OUT: u16 = 0x21;
+const TRAP_PUTS: u16 = 0x22;
+const TRAP_IN: u16 = 0x23;
+const TRAP_PUTS_TWO: u16 = 0x24;
+const TRAP_HALT: u16 = 0x25;
+
+const POSITIVE: u16 = 1 << 0;
+const ZERO: u16 = 1 << 1;
+const NEGATIVE: u16 = 1 << 2;
+
+struct PendingInterrupt {
+    vector: u16,
+    priority: u16,
+}
+
+/// The decoded mnemonic and fetch address produced by a single `step`,
+/// handed back so a caller (or the debugger prompt) can display what
+/// just ran without re-decoding it.
+pub struct StepOutcome {
+    pub pc: u16,
+    pub instr: u16,
+    pub mnemonic: String,
+}
+
+pub struct VM {
+    memory: [u16; std::u16::MAX as usize + 1],
+    registers: [u16; Registers::Count as usize + 1],
+    running: bool,
+    devices: Vec<(std::ops::Range<u16>, Box<dyn Device>)>,
+    interrupt_queue: Vec<PendingInterrupt>,
+    saved_user_sp: u16,
+    breakpoints: HashSet<u16>,
+    stepping: bool,
+    /// Processor status register. Kept as VM state rather than a
+    /// memory-mapped address: the real LC-3 PSR is a dedicated CPU
+    /// register, and mapping it into `self.memory` would let an ordinary
+    /// user-mode `STR`/`STI` self-grant supervisor mode or mask interrupts
+    /// by writing the priority field directly.
+    psr: u16,
+}
+
+impl VM {
+    pub fn new() -> VM {
+        let mut vm = VM {
+            registers: [0; Registers::Count as usize + 1],
+            memory: [0; std::u16::MAX as usize + 1],
+            running: false,
+            devices: Vec::new(),
+            interrupt_queue: Vec::new(),
+            saved_user_sp: 0,
+            breakpoints: HashSet::new(),
+            stepping: false,
+            psr: 0,
+        };
+        vm.attach_device(
+            KEYBOARD_STATUS_REGISTER..(KEYBOARD_DATA_REGISTER + 1),
+            Box::new(KeyboardDevice::new()),
+        );
+        vm.attach_device(
+            DISPLAY_STATUS_REGISTER..(DISPLAY_DATA_REGISTER + 1),
+            Box::new(DisplayDevice),
+        );
+        vm
+    }
+
+    /// Maps a device into the given address range. Later calls take
+    /// priority over earlier ones for overlapping ranges, since devices
+    /// are consulted most-recently-attached first.
+    pub fn attach_device(&mut self, range: std::ops::Range<u16>, device: Box<dyn Device>) {
+        self.devices.push((range, device));
+    }
+
+    /// Loads `program` as a pre-assembled object file and runs it; see
+    /// `run` for the fetch/decode/execute loop itself.
+    pub fn start(&mut self, program: File) -> Result<(), ExecError> {
+        self.read_program(program)?;
+        self.run()
+    }
+
+    /// Drives the fetch/decode/execute cycle through `step` until `halt`,
+    /// assuming a program has already been loaded into memory (by
+    /// `start` or `assemble_and_load`). When a breakpoint is hit (or
+    /// we're already single-stepping) control passes to
+    /// `debugger_prompt` instead of looping straight through.
+    pub fn run(&mut self) -> Result<(), ExecError> {
+        let start_position: u16 = 0x3000;
+
+        self.registers[Registers::ProgramCounter as usize] = start_position;
+        self.set_psr(PSR_MODE_USER | ZERO);
+
+        self.running = true;
+        while self.running {
+            let outcome = self.step()?;
+            if self.stepping || self.breakpoints.contains(&outcome.pc) {
+                self.debugger_prompt(&outcome)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetches, decodes, and executes exactly one instruction, ticking
+    /// devices and taking any newly-raised interrupt afterward, exactly
+    /// as `start`'s loop body used to. Returns the decoded mnemonic and
+    /// the PC it was fetched from, so a caller can trace a program one
+    /// instruction at a time without recompiling the crate.
+    pub fn step(&mut self) -> Result<StepOutcome, ExecError> {
+        let pc = self.registers[Registers::ProgramCounter as usize];
+        let instr = self.mem_read(pc)?;
+        let op = instr >> 12;
+        self.registers[Registers::ProgramCounter as usize] = pc.wrapping_add(1); // Post increment program counter
+
+        match OperationCodes::from_integer(op) {
+            OperationCodes::Add => self.add(instr)?,
+            OperationCodes::And => self.and(instr)?,
+            OperationCodes::Not => self.not(instr)?,
+            OperationCodes::Branch => self.branch(instr)?,
+            OperationCodes::Jump => self.jump(instr)?,
+            OperationCodes::JumpRegister => self.jump_register(instr)?,
+            OperationCodes::Load => self.load(instr)?,
+            OperationCodes::LoadIndirect => self.load_indirect(instr)?,
+            OperationCodes::LoadRegister => self.load_register(instr)?,
+            OperationCodes::LoadEffectiveAddress => self.load_effective_address(instr)?,
+            OperationCodes::Store => self.store(instr)?,
+            OperationCodes::StoreIndirect => self.store_indirect(instr)?,
+            OperationCodes::StoreRegister => self.store_register(instr)?,
+            OperationCodes::Trap => self.trap(instr)?,
+            OperationCodes::Rti => self.rti()?,
+            _ => return Err(ExecError::IllegalOpcode { pc, instr }),
+        }
+
+        for (_, device) in self.devices.iter_mut() {
+            if let Some((vector, priority)) = device.tick() {
+                self.interrupt_queue.push(PendingInterrupt { vector, priority });
+            }
+        }
+        self.maybe_take_interrupt()?;
+
+        Ok(StepOutcome { pc, instr, mnemonic: disassemble(instr) })
+    }
+
+    /// Registers `addr` so `start` drops into the debugger prompt once
+    /// the instruction fetched from it has executed.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Renders every general-purpose register plus PC and the condition
+    /// codes, one per line, for display in the debugger prompt.
+    pub fn dump_registers(&self) -> String {
+        let mut out = String::new();
+        for i in 0..=7 {
+            out.push_str(&format!("R{}: {:#06x}\n", i, self.registers[i]));
+        }
+        out.push_str(&format!("PC: {:#06x}\n", self.registers[Registers::ProgramCounter as usize]));
+        out.push_str(&format!("COND: {:#05b}\n", self.registers[Registers::Condition as usize]));
+        out
+    }
+
+    /// Interactive breakpoint handler: prints the instruction just
+    /// executed and reads commands from stdin until told to resume.
+    /// `continue` runs free until the next breakpoint; `step` stops
+    /// again after every subsequent instruction; `inspect <addr>` reads
+    /// a memory cell and `registers` dumps the register file, neither
+    /// otherwise advancing the VM.
+    fn debugger_prompt(&mut self, outcome: &StepOutcome) -> Result<(), ExecError> {
+        loop {
+            println!("stopped at {:#06x}: {}", outcome.pc, outcome.mnemonic);
+            print!("(c)ontinue/(s)tep/(i)nspect <addr>/(r)egisters> ");
+            stdout().flush()?;
+
+            let mut line = String::new();
+            if stdin().read_line(&mut line)? == 0 {
+                self.running = false;
+                return Ok(());
+            }
+
+            match line.trim() {
+                "c" | "continue" => {
+                    self.stepping = false;
+                    return Ok(());
+                }
+                "s" | "step" => {
+                    self.stepping = true;
+                    return Ok(());
+                }
+                "r" | "registers" => {
+                    print!("{}", self.dump_registers());
+                }
+                cmd => {
+                    if let Some(addr) = cmd.strip_prefix("i ").or_else(|| cmd.strip_prefix("inspect ")) {
+                        match parse_addr(addr.trim()) {
+                            Some(addr) => println!("mem[{:#06x}] = {:#06x}", addr, self.memory[addr as usize]),
+                            None => println!("could not parse address `{}`", addr.trim()),
+                        }
+                    } else {
+                        println!("unknown command `{}` (expected continue/step/inspect <addr>)", cmd);
+                    }
+                }
+            }
+        }
+    }
+
+    fn psr(&self) -> u16 {
+        self.psr
+    }
+
+    fn set_psr(&mut self, val: u16) {
+        self.psr = val;
+    }
+
+    fn psr_mode_user(&self) -> bool {
+        self.psr() & PSR_MODE_USER != 0
+    }
+
+    fn psr_priority(&self) -> u16 {
+        (self.psr() & PSR_PRIORITY_MASK) >> PSR_PRIORITY_SHIFT
+    }
+
+    fn push_supervisor_stack(&mut self, val: u16) {
+        self.registers[Registers::R6 as usize] =
+            self.registers[Registers::R6 as usize].wrapping_sub(1);
+        let addr = self.registers[Registers::R6 as usize];
+        self.mem_write(addr, val);
+    }
+
+    fn pop_supervisor_stack(&mut self) -> Result<u16, ExecError> {
+        let addr = self.registers[Registers::R6 as usize];
+        let val = self.mem_read(addr)?;
+        self.registers[Registers::R6 as usize] =
+            self.registers[Registers::R6 as usize].wrapping_add(1);
+        Ok(val)
+    }
+
+    /// Saves PC/PSR on the supervisor stack, switches to supervisor mode
+    /// at `priority`, and transfers control to the handler named by
+    /// `vector` in the interrupt vector table.
+    fn enter_interrupt(&mut self, vector: u16, priority: u16) -> Result<(), ExecError> {
+        if self.psr_mode_user() {
+            self.saved_user_sp = self.registers[Registers::R6 as usize];
+            self.registers[Registers::R6 as usize] = SUPERVISOR_STACK_BASE;
+        }
+        let psr = self.psr();
+        self.push_supervisor_stack(psr);
+        let pc = self.registers[Registers::ProgramCounter as usize];
+        self.push_supervisor_stack(pc);
+
+        self.set_psr((priority << PSR_PRIORITY_SHIFT) & PSR_PRIORITY_MASK);
+        let vector_addr = IVT_BASE.wrapping_add(vector);
+        self.registers[Registers::ProgramCounter as usize] = self.mem_read(vector_addr)?;
+        Ok(())
+    }
+
+    /// Takes the highest-priority queued interrupt whose priority
+    /// exceeds the current priority level, if any.
+    fn maybe_take_interrupt(&mut self) -> Result<(), ExecError> {
+        let current_priority = self.psr_priority();
+        let next = self
+            .interrupt_queue
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.priority > current_priority)
+            .max_by_key(|(_, i)| i.priority)
+            .map(|(idx, _)| idx);
+        if let Some(idx) = next {
+            let interrupt = self.interrupt_queue.remove(idx);
+            self.enter_interrupt(interrupt.vector, interrupt.priority)?;
+        }
+        Ok(())
+    }
+
+    fn rti(&mut self) -> Result<(), ExecError> {
+        if self.psr_mode_user() {
+            return self.enter_interrupt(VECTOR_PRIVILEGE_VIOLATION, EXCEPTION_PRIORITY);
+        }
+        let pc = self.pop_supervisor_stack()?;
+        let psr = self.pop_supervisor_stack()?;
+        self.registers[Registers::ProgramCounter as usize] = pc;
+        let returning_to_user = psr & PSR_MODE_USER != 0;
+        self.set_psr(psr);
+        if returning_to_user {
+            self.registers[Registers::R6 as usize] = self.saved_user_sp;
+        }
+        Ok(())
+    }
+
+    /// Assembles LC-3 source text and loads it at its `.ORIG` address,
+    /// the way `read_program` loads a pre-assembled object file.
+    pub fn assemble_and_load(&mut self, source: &str) -> Result<(), Vec<crate::asm::AsmError>> {
+        let object = crate::asm::assemble(source)?;
+        let origin = object[0];
+        for (i, word) in object[1..].iter().enumerate() {
+            self.memory[origin.wrapping_add(i as u16) as usize] = *word;
+        }
+        Ok(())
+    }
+
+    fn read_program(&mut self, mut program: File) -> Result<(), ExecError> {
+        let mut buffer: [u8; 2] = [0; 2];
+        program.read_exact(&mut buffer).map_err(|e| {
+            ExecError::Io(std::io::Error::new(
+                e.kind(),
+                "could not read program origin word",
+            ))
+        })?;
+        let mut origin = swap_endian(buffer);
+        loop {
+            match program.read(&mut buffer) {
+                Ok(2) => {
+                    self.memory[origin as usize] = swap_endian(buffer);
+                    origin = origin + 1;
+                }
+                Ok(0) => break,
+                Ok(_) => {
+                    return Err(ExecError::Io(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "unexpected byte count while reading program",
+                    )));
+                }
+                Err(e) => return Err(ExecError::Io(e)),
+            }
+        }
+        Ok(())
+    }
+
+            /// ### Assembler Formats
+    /// **ADD DR, SR1, SR2 \
+    /// ADD DR, SR1, imm5**
+    ///
+    /// ### Encodings
+    /// | 0001  | DR   | SR1  | 0   | 00  | SR2 |
+    /// |-------|------|------|-----|-----|-----|
+    /// | 15...12 | 11..9 | 8..6 | 5 | 4..3 | 2..0 |
+    ///
+    ///
+    /// | 0001  | DR   | SR1  | 1   | imm5  |
+    /// |-------|------|------|-----|-----|
+    /// | 15...12 | 11..9 | 8..6 | 5 | 4..0|
+    /// ### Operation
+    /// if (bit[5] == 0)
+    /// 	DR = SR1 + SR2;
+    /// else
+    /// 	DR = SR1 + SEXT(imm5);
+    /// setcc();
+    ///
+    /// ### Description
+    /// If bit [5] is 0, the second source operand is obtained from SR2. If bit [5] is 1, the second source operand is obtained by sign-extending the imm5 field to 16 bits. In both cases, the second source operand is added to the contents of SR1 and the result stored in DR. The condition codes are set, based on whether the result is negative, zero, or positive.
+    ///
+    /// ### Examples
+    /// ADD R2, R3, R4 ; R2 ← R3 + R4
+    /// ```rust
+    /// let mut vm = VM::new();
+    /// vm.registers[Registers::R2 as usize] = 0;
+    /// vm.registers[Registers::R3 as usize] = 1;
+    /// vm.registers[Registers::R4 as usize] = 3;
+    /// vm.add(0b0001_010_011_0_00_100).unwrap();
+    ///
+    /// assert_eq!(vm.registers[Registers::R2 as usize], 4, "Could not add indirectly!");
+    /// assert_eq!(vm.registers[Registers::Condition as usize], POSITIVE, "Condition register not updated correctly!")
+    /// ```
+    /// ADD R2, R3, #-14 ; R2 ← R3 + (-14)
+    /// ```rust
+    /// let mut vm = VM::new();
+    /// vm.registers[Registers::R2 as usize] = 0;
+    /// vm.registers[Registers::R3 as usize] = 1;
+    /// vm.add(0b0001_010_011_1_10010).unwrap();
+    ///
+    /// assert_eq!(vm.registers[Registers::R2 as usize], 65523, "Could not add immediately!"); //Two's complement
+    /// assert_eq!(vm.registers[Registers::Condition as usize], NEGATIVE, "Condition register not updated correctly!")
+    /// ```
+    fn add(&mut self, instr: u16) -> Result<(), ExecError> {
+        let r0 = decode_bits_11_9(instr);
+        let r1 = decode_bits_8_6(instr);
+        /* whether we are in immediate mode */
+        let imm_flag = (instr >> 5) & 0x1;
+
+        if imm_flag > 0 {
+            let imm5 = decode_signed(instr, 5);
+            self.registers[r0 as usize] = self.registers[r1 as usize].wrapping_add(imm5);
+        } else {
+            let r2 = instr & 0x7;
+            self.registers[r0 as usize] = self.registers[r1 as usize].wrapping_add(self.registers[r2 as usize]);
+        }
+        self.update_flags(r0);
+        Ok(())
+    }
+
+    fn and(&mut self, instr: u16) -> Result<(), ExecError> {
+        let r0 = decode_bits_11_9(instr);
+        let r1 = decode_bits_8_6(instr);
+        /* whether we are in immediate mode */
+        let imm_flag = (instr >> 5) & 0x1;
+        if imm_flag > 0 {
+            let imm5 = decode_signed(instr, 5);
+            self.registers[r0 as usize] = self.registers[r1 as usize] & imm5;
+        } else {
+            let r2 = instr & 0x7;
+            self.registers[r0 as usize] = self.registers[r1 as usize] & self.registers[r2 as usize];
+        }
+        self.update_flags(r0);
+        Ok(())
+    }
+
+    fn not(&mut self, instr: u16) -> Result<(), ExecError> {
+        let r0 = decode_bits_11_9(instr);
+        let r1 = decode_bits_8_6(instr);
+
+        self.registers[r0 as usize] = !(self.registers[r1 as usize]);
+        self.update_flags(r0);
+        Ok(())
+    }
+
+    fn branch(&mut self, instr: u16) -> Result<(), ExecError> {
+        let pc_offset = decode_signed(instr, 9);
+        let cond_flag = decode_bits_11_9(instr);
+        if cond_flag & self.registers[Registers::Condition as usize] > 0 {
+            self.registers[Registers::ProgramCounter as usize] =
+                self.registers[Registers::ProgramCounter as usize].wrapping_add(pc_offset);
+        }
+        Ok(())
+    }
+
+    fn jump(&mut self, instr: u16) -> Result<(), ExecError> {
+        /* Also handles RET */
+        let r1 = decode_bits_8_6(instr);
+        self.registers[Registers::ProgramCounter as usize] = self.registers[r1 as usize];
+        Ok(())
+    }
+
+    fn jump_register(&mut self, instr: u16) -> Result<(), ExecError> {
+        let base_value = self.registers[decode_bits_8_6(instr) as usize];
+        self.registers[Registers::R7 as usize] = self.registers[Registers::ProgramCounter as usize];
+        let jsr = (instr >> 11) & 1;
+        if jsr > 0 {
+            let pc_offset = decode_signed(instr, 11);
+            self.registers[Registers::ProgramCounter as usize] =
+                self.registers[Registers::ProgramCounter as usize].wrapping_add(pc_offset);
+        } else {
+            //jsrr
+            self.registers[Registers::ProgramCounter as usize] = base_value;
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, instr: u16) -> Result<(), ExecError> {
+        let r0 = decode_bits_11_9(instr);
+        let pc_offset = decode_signed(instr, 9);
+        /* add pc_offset to the current PC and load the value at that memory location */
+        let loaded = self.mem_read(self.registers[Registers::ProgramCounter as usize].wrapping_add(pc_offset))?;
+        self.registers[r0 as usize] = loaded;
+        self.update_flags(r0);
+        Ok(())
+    }
+
+    fn update_flags(&mut self, r: u16) {
+        let r_val = self.registers[r as usize];
+        let cc = if r_val == 0 {
+            ZERO
+        } else if (r_val >> 15) > 0 {
+            NEGATIVE
+        } else {
+            POSITIVE
+        };
+        self.registers[Registers::Condition as usize] = cc;
+        let psr = self.psr();
+        self.set_psr((psr & !0x7) | cc);
+    }
+
+    fn load_indirect(&mut self, instr: u16) -> Result<(), ExecError> {
+        let r0 = decode_bits_11_9(instr);
+        let pc_offset = decode_signed(instr, 9);
+        /* add pc_offset to the current PC, look at that memory location to get the final address */
+        let read = self.mem_read(self.registers[Registers::ProgramCounter as usize].wrapping_add(pc_offset))?;
+        self.registers[r0 as usize] = self.mem_read(read)?;
+        self.update_flags(r0);
+        Ok(())
+    }
+
+    fn load_register(&mut self, instr: u16) -> Result<(), ExecError> {
+        let offset = decode_signed(instr, 6);
+        let dr = decode_bits_11_9(instr);
+        let baser = decode_bits_8_6(instr);
+
+        self.registers[dr as usize] = self.mem_read(self.registers[baser as usize].wrapping_add(offset))?;
+        self.update_flags(dr);
+        Ok(())
+    }
+
+    fn load_effective_address(&mut self, instr: u16) -> Result<(), ExecError> {
+        let r0 = decode_bits_11_9(instr);
+        let pc_offset = decode_signed(instr, 9);
+        /* add pc_offset to the current PC; the address itself goes into DR, no memory is read */
+        self.registers[r0 as usize] =
+            self.registers[Registers::ProgramCounter as usize].wrapping_add(pc_offset);
+        self.update_flags(r0);
+        Ok(())
+    }
+
+    fn store(&mut self, instr: u16) -> Result<(), ExecError> {
+        let sr = decode_bits_11_9(instr);
+        let pc_offset = decode_signed(instr, 9);
+        self.mem_write(
+            self.registers[Registers::ProgramCounter as usize].wrapping_add(pc_offset),
+            self.registers[sr as usize],
+        );
+        Ok(())
+    }
+
+    fn store_indirect(&mut self, instr: u16) -> Result<(), ExecError> {
+        let sr = decode_bits_11_9(instr);
+        let pc_offset = decode_signed(instr, 9);
+        let read = self.mem_read(self.registers[Registers::ProgramCounter as usize].wrapping_add(pc_offset))?;
+        self.mem_write(read, self.registers[sr as usize]);
+        Ok(())
+    }
+
+    fn store_register(&mut self, instr: u16) -> Result<(), ExecError> {
+        let sr = decode_bits_11_9(instr);
+        let offset = decode_signed(instr, 6);
+        let baser = decode_bits_8_6(instr);
+        self.mem_write(self.registers[baser as usize].wrapping_add(offset), self.registers[sr as usize]);
+        Ok(())
+    }
+
+    fn trap(&mut self, instr: u16) -> Result<(), ExecError> {
+        match instr & 0xFF {
+            TRAP_GET_CHARACTER => self.get_character(),
+            TRAP_OUT => self.out(),
+            TRAP_PUTS => self.puts(),
+            TRAP_IN => self.scan(),
+            TRAP_PUTS_TWO => self.putsp(),
+            TRAP_HALT => self.halt(),
+            _ => Err(ExecError::UnknownTrap {
+                /* PC was already post-incremented past the TRAP */
+                pc: self.registers[Registers::ProgramCounter as usize].wrapping_sub(1),
+                instr,
+            }),
+        }
+    }
+
+    fn get_character(&mut self) -> Result<(), ExecError> {
+        //TODO ignore enter
+        let input: u16 = std::io::stdin()
+            .bytes()
+            .next()
+            .transpose()?
+            .map(|byte| byte as u16)
+            .ok_or_else(|| {
+                ExecError::Io(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "could not read character",
+                ))
+            })?;
+        self.registers[Registers::R0 as usize] = input & 0b1111_1111;
+        Ok(())
+    }
+
+    fn out(&mut self) -> Result<(), ExecError> {
+        let ch = self.registers[Registers::R0 as usize] & 0b1111_1111;
+        self.mem_write(DISPLAY_DATA_REGISTER, ch);
+        Ok(())
+    }
+
+    fn puts(&mut self) -> Result<(), ExecError> {
+        let mut addr = self.registers[Registers::R0 as usize];
+        let mut character = self.memory[addr as usize];
+
+        while character > 0 {
+            self.mem_write(DISPLAY_DATA_REGISTER, character & 0b1111_1111); //Hmmmm.......
+            addr = addr + 1;
+            character = self.memory[addr as usize];
+        }
+        Ok(())
+    }
+
+    // in
+    fn scan(&mut self) -> Result<(), ExecError> {
+        print!("Enter a character: ");
+        stdout().flush()?;
+
+        self.get_character()
+    }
+
+    fn putsp(&mut self) -> Result<(), ExecError> {
+        let mut addr = self.registers[Registers::R0 as usize];
+        let mut character = self.memory[addr as usize];
+
+        while character > 0 {
+            self.mem_write(DISPLAY_DATA_REGISTER, character & 0b1111_1111);
+            let second_part = (character >> 8) & 0b1111_1111;
+            if second_part == 0 {
+                break;
+            }
+            self.mem_write(DISPLAY_DATA_REGISTER, second_part);
+            addr = addr + 1;
+            character = self.memory[addr as usize];
+        }
+        Ok(())
+    }
+
+    fn halt(&mut self) -> Result<(), ExecError> {
+        println!("Goodbye!");
+        self.running = false;
+        Ok(())
+    }
+
+    fn mem_write(&mut self, adress: u16, val: u16) {
+        for (range, device) in self.devices.iter_mut().rev() {
+            if range.contains(&adress) && device.write(adress, val) {
+                return;
+            }
+        }
+        self.memory[adress as usize] = val;
+    }
+
+    fn mem_read(&mut self, address: u16) -> Result<u16, ExecError> {
+        for (range, device) in self.devices.iter_mut().rev() {
+            if range.contains(&address) {
+                if let Some(val) = device.read(address) {
+                    return Ok(val);
+                }
+            }
+        }
+        Ok(self.memory[address as usize])
+    }
+}
+
+fn swap_endian(original: [u8; 2]) -> u16 {
+    original[1] as u16 + ((original[0] as u16) << 8) //TODO the right way?
+}
+
+/// Register field in bits [11:9] — DR/SR for most opcodes, the branch
+/// condition flags for BR. Shared by each handler and `disassemble` so
+/// they can't drift apart on the field's position or width.
+fn decode_bits_11_9(instr: u16) -> u16 {
+    (instr >> 9) & 0x7
+}
+
+/// Register field in bits [8:6] — SR1/BaseR for every opcode that reads
+/// a second register operand from there. Shared by each handler and
+/// `disassemble`.
+fn decode_bits_8_6(instr: u16) -> u16 {
+    (instr >> 6) & 0x7
+}
+
+/// Sign-extends the low `bits` bits of `instr` — the shape shared by the
+/// imm5/offset6/PCoffset9/PCoffset11 fields, differing only in width.
+/// Shared by each handler and `disassemble`.
+fn decode_signed(instr: u16, bits: i32) -> u16 {
+    sign_extend(instr & ((1u16 << bits) - 1), bits)
+}
+
+/// Decodes a raw instruction word into the mnemonic form an assembly
+/// listing would show, reusing the same `decode_bits_11_9`/`decode_bits_8_6`/
+/// `decode_signed` helpers each handler above uses to pull the instruction
+/// apart, so the two can't disagree about a field's position or width. Used
+/// by the debugger to report what `step` just ran without duplicating any
+/// execution logic.
+pub fn disassemble(instr: u16) -> String {
+    let op = instr >> 12;
+    match OperationCodes::from_integer(op) {
+        OperationCodes::Add => {
+            let dr = decode_bits_11_9(instr);
+            let sr1 = decode_bits_8_6(instr);
+            if (instr >> 5) & 0x1 > 0 {
+                let imm5 = decode_signed(instr, 5) as i16;
+                format!("ADD R{}, R{}, #{}", dr, sr1, imm5)
+            } else {
+                format!("ADD R{}, R{}, R{}", dr, sr1, instr & 0x7)
+            }
+        }
+        OperationCodes::And => {
+            let dr = decode_bits_11_9(instr);
+            let sr1 = decode_bits_8_6(instr);
+            if (instr >> 5) & 0x1 > 0 {
+                let imm5 = decode_signed(instr, 5) as i16;
+                format!("AND R{}, R{}, #{}", dr, sr1, imm5)
+            } else {
+                format!("AND R{}, R{}, R{}", dr, sr1, instr & 0x7)
+            }
+        }
+        OperationCodes::Not => {
+            let dr = decode_bits_11_9(instr);
+            let sr = decode_bits_8_6(instr);
+            format!("NOT R{}, R{}", dr, sr)
+        }
+        OperationCodes::Branch => {
+            let pc_offset = decode_signed(instr, 9) as i16;
+            let mut flags = String::new();
+            if (instr >> 11) & 1 > 0 {
+                flags.push('n');
+            }
+            if (instr >> 10) & 1 > 0 {
+                flags.push('z');
+            }
+            if (instr >> 9) & 1 > 0 {
+                flags.push('p');
+            }
+            format!("BR{} #{}", flags, pc_offset)
+        }
+        OperationCodes::Jump => {
+            let r1 = decode_bits_8_6(instr);
+            if r1 == 7 {
+                "RET".to_string()
+            } else {
+                format!("JMP R{}", r1)
+            }
+        }
+        OperationCodes::JumpRegister => {
+            if (instr >> 11) & 1 > 0 {
+                let pc_offset = decode_signed(instr, 11) as i16;
+                format!("JSR #{}", pc_offset)
+            } else {
+                format!("JSRR R{}", decode_bits_8_6(instr))
+            }
+        }
+        OperationCodes::Load => {
+            let dr = decode_bits_11_9(instr);
+            let pc_offset = decode_signed(instr, 9) as i16;
+            format!("LD R{}, #{}", dr, pc_offset)
+        }
+        OperationCodes::LoadIndirect => {
+            let dr = decode_bits_11_9(instr);
+            let pc_offset = decode_signed(instr, 9) as i16;
+            format!("LDI R{}, #{}", dr, pc_offset)
+        }
+        OperationCodes::LoadRegister => {
+            let dr = decode_bits_11_9(instr);
+            let baser = decode_bits_8_6(instr);
+            let offset = decode_signed(instr, 6) as i16;
+            format!("LDR R{}, R{}, #{}", dr, baser, offset)
+        }
+        OperationCodes::LoadEffectiveAddress => {
+            let dr = decode_bits_11_9(instr);
+            let pc_offset = decode_signed(instr, 9) as i16;
+            format!("LEA R{}, #{}", dr, pc_offset)
+        }
+        OperationCodes::Store => {
+            let sr = decode_bits_11_9(instr);
+            let pc_offset = decode_signed(instr, 9) as i16;
+            format!("ST R{}, #{}", sr, pc_offset)
+        }
+        OperationCodes::StoreIndirect => {
+            let sr = decode_bits_11_9(instr);
+            let pc_offset = decode_signed(instr, 9) as i16;
+            format!("STI R{}, #{}", sr, pc_offset)
+        }
+        OperationCodes::StoreRegister => {
+            let sr = decode_bits_11_9(instr);
+            let baser = decode_bits_8_6(instr);
+            let offset = decode_signed(instr, 6) as i16;
+            format!("STR R{}, R{}, #{}", sr, baser, offset)
+        }
+        OperationCodes::Trap => match instr & 0xFF {
+            TRAP_GET_CHARACTER => "GETC".to_string(),
+            TRAP_OUT => "OUT".to_string(),
+            TRAP_PUTS => "PUTS".to_string(),
+            TRAP_IN => "IN".to_string(),
+            TRAP_PUTS_TWO => "PUTSP".to_string(),
+            TRAP_HALT => "HALT".to_string(),
+            vector => format!("TRAP x{:02X}", vector),
+        },
+        OperationCodes::Rti => "RTI".to_string(),
+        OperationCodes::Reserved => format!("RESERVED {:#06x}", instr),
+    }
+}
+
+/// Parses a debugger-prompt address argument, accepting either a bare
+/// decimal number or a `0x`/`x`-prefixed hex literal.
+fn parse_addr(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('x')) {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse::<u16>().ok()
+    }
+}
+
+fn sign_extend(x: u16, bit_count: i32) -> u16 {
+    let mut y = x;
+    // for negative numbers
+    if ((y >> (bit_count - 1)) & 1) > 0 {
+        y |= 0xFFFF << bit_count;
+    }
+    y
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_sign_extend() {
+        assert_eq!(sign_extend(0b1, 1), 65535, "Could not correctly sign extend number!");
+        assert_eq!(sign_extend(0b101, 3), 65533, "Could not correctly sign extend number!");
+    }
+
+    #[test]
+    fn can_swap_endian() {
+        assert_eq!(swap_endian([0b00000000, 0b11111111]), 0b00000000_11111111, "Could not swap endianness!")
+    }
+
+    #[test]
+    fn can_add_indirect() {
+        let mut vm = VM::new();
+        vm.registers[Registers::R2 as usize] = 0;
+        vm.registers[Registers::R3 as usize] = 1;
+        vm.registers[Registers::R4 as usize] = 3;
+        vm.add(0b0001_010_011_0_00_100).unwrap();
+
+        assert_eq!(vm.registers[Registers::R2 as usize], 4, "Could not add indirectly!");
+        assert_eq!(vm.registers[Registers::Condition as usize], POSITIVE, "Condition register not updated correctly!")
+    }
+
+    #[test]
+    fn can_add_immediate() {
+        let mut vm = VM::new();
+        vm.registers[Registers::R2 as usize] = 0;
+        vm.registers[Registers::R3 as usize] = 1;
+        vm.add(0b0001_010_011_1_10010).unwrap();
+
+        assert_eq!(vm.registers[Registers::R2 as usize], 65523, "Could not add immediately!"); //Two's complement
+        assert_eq!(vm.registers[Registers::Condition as usize], NEGATIVE, "Condition register not updated correctly!")
+    }
+
+    #[test]
+    fn can_disassemble_add() {
+        assert_eq!(disassemble(0b0001_010_011_0_00_100), "ADD R2, R3, R4");
+        assert_eq!(disassemble(0b0001_010_011_1_10010), "ADD R2, R3, #-14");
+    }
+
+    #[test]
+    fn can_disassemble_branch_and_halt() {
+        assert_eq!(disassemble(0b0000_111_000000101), "BRnzp #5");
+        assert_eq!(disassemble(0b1111_0000_00100101), "HALT");
+    }
+
+    #[test]
+    fn timer_interrupt_preempts_after_its_period() {
+        let mut vm = VM::new();
+        vm.attach_device(0..0, Box::new(TimerDevice::new(3)));
+
+        let handler_addr = 0x4000;
+        vm.memory[IVT_BASE.wrapping_add(VECTOR_TIMER) as usize] = handler_addr;
+        vm.registers[Registers::ProgramCounter as usize] = 0x3000;
+        vm.set_psr(PSR_MODE_USER | ZERO);
+
+        for _ in 0..3 {
+            vm.step().unwrap();
+        }
+
+        assert_eq!(
+            vm.registers[Registers::ProgramCounter as usize],
+            handler_addr,
+            "timer device did not preempt into its ISR after its period elapsed"
+        );
+    }
+}
@@ -1,14 +1,60 @@
 use std::env;
+use std::fs;
 use std::fs::File;
+use std::process::ExitCode;
 
+mod asm;
 mod lc3_vm;
 
-fn main() {
+fn main() -> ExitCode {
     let mut vm = lc3_vm::VM::new();
 
     let args: Vec<String> = env::args().collect();
-    let location = &args[1];
-    let program = File::open(location).expect("Could not open program");
+    let mut assemble = false;
+    let mut breakpoints: Vec<u16> = Vec::new();
+    let mut location: Option<&String> = None;
 
-    vm.start(program);
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--asm" => assemble = true,
+            "--break" => {
+                i += 1;
+                let addr = args.get(i).expect("--break requires a hex address");
+                breakpoints.push(
+                    u16::from_str_radix(addr.trim_start_matches("0x").trim_start_matches('x'), 16)
+                        .expect("--break address must be hex"),
+                );
+            }
+            _ => location = Some(&args[i]),
+        }
+        i += 1;
+    }
+    let location = location.expect("usage: lc3_vm [--asm] [--break <addr>]... <program>");
+
+    for addr in breakpoints {
+        vm.add_breakpoint(addr);
+    }
+
+    let result = if assemble {
+        let source = fs::read_to_string(location).expect("Could not read assembly source");
+        vm.assemble_and_load(&source).unwrap_or_else(|errors| {
+            for e in &errors {
+                eprintln!("{}", e);
+            }
+            std::process::exit(1);
+        });
+        vm.run()
+    } else {
+        let program = File::open(location).expect("Could not open program");
+        vm.start(program)
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("VM halted: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }